@@ -1,132 +1,325 @@
 use core::cmp::Ordering;
-use serde::{Deserialize, Serialize};
+use core::fmt;
+use core::ops::{BitAnd, BitOr, BitXor, Not};
+use core::str::FromStr;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serializer};
 use vsdb::{impl_vs_methods_nope, VsMgmt};
 
-/// Represent 256 bits
-#[derive(Eq, PartialEq, Debug, Default, Hash, Clone, Copy, Deserialize, Serialize)]
-pub struct H256([u8; 32]);
-
-const ZERO: H256 = H256([0u8; 32]);
 const BYTE_SIZE: u8 = 8;
 
-impl H256 {
-    #[inline(always)]
-    pub const fn zero() -> Self {
-        ZERO
-    }
+/// Error returned when parsing a fixed-hash from a hex string.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ParseHashError {
+    /// The hex payload did not decode to the expected byte width.
+    InvalidLength,
+    /// The string contained a non-hex character.
+    InvalidHex,
+}
 
-    #[inline(always)]
-    pub fn is_zero(&self) -> bool {
-        self == &ZERO
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseHashError::InvalidLength => f.write_str("invalid hash length"),
+            ParseHashError::InvalidHex => f.write_str("invalid hex character"),
+        }
     }
+}
 
-    #[inline(always)]
-    pub fn get_bit(&self, i: u8) -> bool {
-        let byte_pos = i / BYTE_SIZE;
-        let bit_pos = i % BYTE_SIZE;
-        let bit = self.0[byte_pos as usize] >> bit_pos & 1;
-        bit != 0
-    }
+/// Generate a width-parametric fixed-hash newtype and its tree-path API.
+///
+/// `$size` is the width in bytes; `$ht` is an integer type wide enough to
+/// address every bit (`0..$size * 8`). Following the `impl_hash!` convention
+/// from parity-common, each invocation emits an identical surface — bit
+/// accessors, the `fork_height`/`parent_path`/`copy_bits` path helpers, a
+/// high-to-low `Ord`, array conversions and the `VsMgmt` impl — so the sparse
+/// Merkle tree can be instantiated over any key width without copy-paste.
+macro_rules! impl_hash {
+    ($name:ident, $size:expr, $ht:ty) => {
+        #[doc = concat!("Represent ", stringify!($size), " bytes")]
+        #[derive(Eq, PartialEq, Debug, Hash, Clone, Copy)]
+        pub struct $name([u8; $size]);
+
+        impl Default for $name {
+            #[inline(always)]
+            fn default() -> Self {
+                Self::zero()
+            }
+        }
 
-    #[inline(always)]
-    pub fn set_bit(&mut self, i: u8) {
-        let byte_pos = i / BYTE_SIZE;
-        let bit_pos = i % BYTE_SIZE;
-        self.0[byte_pos as usize] |= 1 << bit_pos as u8;
-    }
+        impl $name {
+            /// The number of addressable bits in the path.
+            const BIT_SIZE: usize = $size * BYTE_SIZE as usize;
 
-    #[inline(always)]
-    pub fn clear_bit(&mut self, i: u8) {
-        let byte_pos = i / BYTE_SIZE;
-        let bit_pos = i % BYTE_SIZE;
-        self.0[byte_pos as usize] &= !((1 << bit_pos) as u8);
-    }
+            #[inline(always)]
+            pub const fn zero() -> Self {
+                $name([0u8; $size])
+            }
 
-    #[inline(always)]
-    pub fn is_right(&self, height: u8) -> bool {
-        self.get_bit(height)
-    }
+            #[inline(always)]
+            pub fn is_zero(&self) -> bool {
+                self == &Self::zero()
+            }
 
-    #[inline(always)]
-    pub fn as_slice(&self) -> &[u8] {
-        &self.0[..]
-    }
+            #[inline(always)]
+            pub fn get_bit(&self, i: $ht) -> bool {
+                let byte_pos = i / BYTE_SIZE as $ht;
+                let bit_pos = i % BYTE_SIZE as $ht;
+                let bit = self.0[byte_pos as usize] >> bit_pos & 1;
+                bit != 0
+            }
 
-    /// Treat H256 as a path in a tree
-    /// fork height is the number of common bits(from heigher to lower: 255..=0) of two H256
-    #[inline(always)]
-    pub fn fork_height(&self, key: &H256) -> u8 {
-        for h in (0..=core::u8::MAX).rev() {
-            if self.get_bit(h) != key.get_bit(h) {
-                return h;
+            #[inline(always)]
+            pub fn set_bit(&mut self, i: $ht) {
+                let byte_pos = i / BYTE_SIZE as $ht;
+                let bit_pos = i % BYTE_SIZE as $ht;
+                self.0[byte_pos as usize] |= 1 << bit_pos;
+            }
+
+            #[inline(always)]
+            pub fn clear_bit(&mut self, i: $ht) {
+                let byte_pos = i / BYTE_SIZE as $ht;
+                let bit_pos = i % BYTE_SIZE as $ht;
+                self.0[byte_pos as usize] &= !((1 << bit_pos) as u8);
+            }
+
+            #[inline(always)]
+            pub fn is_right(&self, height: $ht) -> bool {
+                self.get_bit(height)
+            }
+
+            #[inline(always)]
+            pub fn as_slice(&self) -> &[u8] {
+                &self.0[..]
+            }
+
+            /// Build a hash with every byte drawn from the thread RNG.
+            #[cfg(feature = "rand")]
+            #[inline(always)]
+            pub fn random() -> Self {
+                Self::random_with(&mut rand::thread_rng())
+            }
+
+            /// Build a hash with every byte drawn from `rng`.
+            #[cfg(feature = "rand")]
+            #[inline(always)]
+            pub fn random_with<R: rand::RngCore + ?Sized>(rng: &mut R) -> Self {
+                let mut h = Self::zero();
+                rng.fill_bytes(&mut h.0);
+                h
+            }
+
+            /// Treat the hash as a path in a tree.
+            /// fork height is the index of the highest bit (scanning from heigher to lower: (N*8-1)..=0)
+            /// at which the two hashes differ; 0 if they are equal
+            #[inline(always)]
+            pub fn fork_height(&self, key: &$name) -> $ht {
+                for h in (0..Self::BIT_SIZE).rev() {
+                    if self.get_bit(h as $ht) != key.get_bit(h as $ht) {
+                        return h as $ht;
+                    }
+                }
+                0
+            }
+
+            /// Treat the hash as a path in a tree.
+            /// return parent_path of self
+            #[inline(always)]
+            pub fn parent_path(&self, height: $ht) -> Self {
+                if height as usize == Self::BIT_SIZE - 1 {
+                    Self::zero()
+                } else {
+                    self.copy_bits(height + 1)
+                }
+            }
+
+            /// Copy bits and return a new hash
+            #[inline(always)]
+            pub fn copy_bits(&self, start: $ht) -> Self {
+                let mut target = Self::zero();
+
+                let start_byte = (start / BYTE_SIZE as $ht) as usize;
+                // copy bytes
+                target.0[start_byte..].copy_from_slice(&self.0[start_byte..]);
+
+                // reset remain bytes
+                let remain = start % BYTE_SIZE as $ht;
+                if remain > 0 {
+                    target.0[start_byte] &= 0b11111111u8 << remain
+                }
+
+                target
             }
         }
-        0
-    }
 
-    /// Treat H256 as a path in a tree
-    /// return parent_path of self
-    #[inline(always)]
-    pub fn parent_path(&self, height: u8) -> Self {
-        if height == core::u8::MAX {
-            H256::zero()
-        } else {
-            self.copy_bits(height + 1)
+        impl VsMgmt for $name {
+            impl_vs_methods_nope! {}
         }
-    }
 
-    /// Copy bits and return a new H256
-    #[inline(always)]
-    pub fn copy_bits(&self, start: u8) -> Self {
-        let mut target = H256::zero();
+        impl PartialOrd for $name {
+            #[inline(always)]
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
 
-        let start_byte = (start / BYTE_SIZE) as usize;
-        // copy bytes
-        target.0[start_byte..].copy_from_slice(&self.0[start_byte..]);
+        impl Ord for $name {
+            #[inline(always)]
+            fn cmp(&self, other: &Self) -> Ordering {
+                // Compare bits from heigher to lower ((N*8-1)..0)
+                self.0.iter().rev().cmp(other.0.iter().rev())
+            }
+        }
 
-        // reset remain bytes
-        let remain = start % BYTE_SIZE;
-        if remain > 0 {
-            target.0[start_byte] &= 0b11111111 << remain
+        impl From<[u8; $size]> for $name {
+            #[inline(always)]
+            fn from(h: [u8; $size]) -> $name {
+                $name(h)
+            }
         }
 
-        target
-    }
-}
+        impl From<&[u8; $size]> for $name {
+            #[inline(always)]
+            fn from(h: &[u8; $size]) -> $name {
+                $name(*h)
+            }
+        }
 
-impl VsMgmt for H256 {
-    impl_vs_methods_nope! {}
-}
+        impl From<$name> for [u8; $size] {
+            #[inline(always)]
+            fn from(h: $name) -> [u8; $size] {
+                h.0
+            }
+        }
 
-impl PartialOrd for H256 {
-    #[inline(always)]
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0[..]
+            }
+        }
 
-impl Ord for H256 {
-    #[inline(always)]
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Compare bits from heigher to lower (255..0)
-        self.0.iter().rev().cmp(other.0.iter().rev())
-    }
-}
+        impl fmt::LowerHex for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if f.alternate() {
+                    f.write_str("0x")?;
+                }
+                for byte in self.0.iter() {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
 
-impl From<[u8; 32]> for H256 {
-    #[inline(always)]
-    fn from(h: [u8; 32]) -> H256 {
-        H256(h)
-    }
-}
+        impl fmt::UpperHex for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if f.alternate() {
+                    f.write_str("0x")?;
+                }
+                for byte in self.0.iter() {
+                    write!(f, "{:02X}", byte)?;
+                }
+                Ok(())
+            }
+        }
 
-impl From<&[u8; 32]> for H256 {
-    #[inline(always)]
-    fn from(h: &[u8; 32]) -> H256 {
-        H256(*h)
-    }
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{:#x}", self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseHashError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let s = s
+                    .strip_prefix("0x")
+                    .or_else(|| s.strip_prefix("0X"))
+                    .unwrap_or(s);
+                if s.len() != $size * 2 {
+                    return Err(ParseHashError::InvalidLength);
+                }
+                let mut bytes = [0u8; $size];
+                for (i, chunk) in s.as_bytes().chunks(2).enumerate() {
+                    let hi = (chunk[0] as char)
+                        .to_digit(16)
+                        .ok_or(ParseHashError::InvalidHex)?;
+                    let lo = (chunk[1] as char)
+                        .to_digit(16)
+                        .ok_or(ParseHashError::InvalidHex)?;
+                    bytes[i] = ((hi << 4) | lo) as u8;
+                }
+                Ok($name(bytes))
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                if serializer.is_human_readable() {
+                    serializer.serialize_str(&format!("{:#x}", self))
+                } else {
+                    // Fixed-length tuple keeps the compact, length-prefix-free
+                    // layout of the old `[u8; $size]` derive so existing binary
+                    // blobs still deserialize.
+                    let mut tup = serializer.serialize_tuple($size)?;
+                    for byte in self.0.iter() {
+                        tup.serialize_element(byte)?;
+                    }
+                    tup.end()
+                }
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                if deserializer.is_human_readable() {
+                    let s = String::deserialize(deserializer)?;
+                    s.parse().map_err(de::Error::custom)
+                } else {
+                    struct ArrayVisitor;
+
+                    impl<'de> Visitor<'de> for ArrayVisitor {
+                        type Value = $name;
+
+                        fn expecting(
+                            &self,
+                            f: &mut fmt::Formatter<'_>,
+                        ) -> fmt::Result {
+                            write!(f, "{} bytes", $size)
+                        }
+
+                        fn visit_seq<A>(self, mut seq: A) -> Result<$name, A::Error>
+                        where
+                            A: SeqAccess<'de>,
+                        {
+                            let mut bytes = [0u8; $size];
+                            for (i, slot) in bytes.iter_mut().enumerate() {
+                                *slot = seq
+                                    .next_element()?
+                                    .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                            }
+                            Ok($name(bytes))
+                        }
+                    }
+
+                    deserializer.deserialize_tuple($size, ArrayVisitor)
+                }
+            }
+        }
+    };
 }
 
+impl_hash!(H160, 20, u8);
+impl_hash!(H256, 32, u8);
+impl_hash!(H512, 64, u16);
+
 impl From<H256> for pt11::H256 {
     #[inline(always)]
     fn from(h: H256) -> pt11::H256 {
@@ -141,13 +334,6 @@ impl From<H256> for pt10::H256 {
     }
 }
 
-impl From<H256> for [u8; 32] {
-    #[inline(always)]
-    fn from(h256: H256) -> [u8; 32] {
-        h256.0
-    }
-}
-
 impl From<pt11::H256> for H256 {
     #[inline(always)]
     fn from(h: pt11::H256) -> Self {
@@ -204,8 +390,256 @@ impl From<&pt10::H160> for H256 {
     }
 }
 
-impl AsRef<[u8]> for H256 {
-    fn as_ref(&self) -> &[u8] {
-        &self.0[..]
+impl BitXor for H256 {
+    type Output = H256;
+    #[inline(always)]
+    fn bitxor(self, rhs: H256) -> H256 {
+        let mut out = H256::zero();
+        for (o, (a, b)) in out.0.iter_mut().zip(self.0.iter().zip(rhs.0.iter())) {
+            *o = a ^ b;
+        }
+        out
+    }
+}
+
+impl BitAnd for H256 {
+    type Output = H256;
+    #[inline(always)]
+    fn bitand(self, rhs: H256) -> H256 {
+        let mut out = H256::zero();
+        for (o, (a, b)) in out.0.iter_mut().zip(self.0.iter().zip(rhs.0.iter())) {
+            *o = a & b;
+        }
+        out
+    }
+}
+
+impl BitOr for H256 {
+    type Output = H256;
+    #[inline(always)]
+    fn bitor(self, rhs: H256) -> H256 {
+        let mut out = H256::zero();
+        for (o, (a, b)) in out.0.iter_mut().zip(self.0.iter().zip(rhs.0.iter())) {
+            *o = a | b;
+        }
+        out
+    }
+}
+
+impl Not for H256 {
+    type Output = H256;
+    #[inline(always)]
+    fn not(self) -> H256 {
+        let mut out = H256::zero();
+        for (o, a) in out.0.iter_mut().zip(self.0.iter()) {
+            *o = !a;
+        }
+        out
+    }
+}
+
+// `Ord` for `H256` compares bytes high-index-to-low, i.e. byte 0 is the least
+// significant limb; the `U256` bridge therefore uses the little-endian layout so
+// that `a < b` iff `U256::from(a) < U256::from(b)`.
+impl From<H256> for pt11::U256 {
+    #[inline(always)]
+    fn from(h: H256) -> pt11::U256 {
+        pt11::U256::from_little_endian(&h.0)
+    }
+}
+
+impl From<pt11::U256> for H256 {
+    #[inline(always)]
+    fn from(u: pt11::U256) -> H256 {
+        let mut bytes = [0u8; 32];
+        u.to_little_endian(&mut bytes);
+        H256(bytes)
+    }
+}
+
+impl H256 {
+    /// The three bloom positions this hash folds into, following Ethereum's
+    /// 3-point scheme: the first three 16-bit big-endian words masked with
+    /// `0x07FF` (log2(2048) = 11 bits), each a position in `0..2048`.
+    #[inline(always)]
+    pub fn bloom_bits(&self) -> [u16; 3] {
+        let mut bits = [0u16; 3];
+        for (i, slot) in bits.iter_mut().enumerate() {
+            let word = ((self.0[i * 2] as u16) << 8) | self.0[i * 2 + 1] as u16;
+            *slot = word & 0x07FF;
+        }
+        bits
+    }
+}
+
+/// A 2048-bit bloom accumulator for negative membership checks over SMT keys.
+///
+/// `accrue` folds a key in via its three [`H256::bloom_bits`]; `contains`
+/// answers "key is definitely absent" (it may false-positive, never
+/// false-negative), and `union` composes child blooms up the tree.
+#[derive(Eq, PartialEq, Debug, Hash, Clone, Copy)]
+pub struct H2048([u8; 256]);
+
+/// Alias matching the parity naming for a 2048-bit bloom.
+pub type Bloom = H2048;
+
+impl H2048 {
+    #[inline(always)]
+    pub const fn zero() -> Self {
+        H2048([0u8; 256])
+    }
+
+    #[inline(always)]
+    pub fn is_zero(&self) -> bool {
+        self == &Self::zero()
+    }
+
+    /// Fold a key into the bloom by setting its three derived bits.
+    #[inline(always)]
+    pub fn accrue(&mut self, key: &H256) {
+        for pos in key.bloom_bits() {
+            self.0[(pos / 8) as usize] |= 1 << (pos % 8);
+        }
+    }
+
+    /// Return `true` only if all three derived bits are set (may false-positive).
+    #[inline(always)]
+    pub fn contains(&self, key: &H256) -> bool {
+        key.bloom_bits()
+            .iter()
+            .all(|&pos| self.0[(pos / 8) as usize] & (1 << (pos % 8)) != 0)
+    }
+
+    /// Merge another bloom into this one.
+    #[inline(always)]
+    pub fn union(&mut self, other: &H2048) {
+        for (dst, src) in self.0.iter_mut().zip(other.0.iter()) {
+            *dst |= *src;
+        }
+    }
+}
+
+impl Default for H2048 {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+impl quickcheck::Arbitrary for H256 {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let mut h = H256::zero();
+        for byte in h.0.iter_mut() {
+            *byte = u8::arbitrary(g);
+        }
+        h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u256_round_trip_and_ordering() {
+        let a = H256::from([
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc,
+            0xdd, 0xee, 0xff, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+            0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10,
+        ]);
+        let b = H256::from([0xffu8; 32]);
+
+        assert_eq!(H256::from(pt11::U256::from(a)), a);
+        assert_eq!(
+            a < b,
+            pt11::U256::from(a) < pt11::U256::from(b),
+            "numeric order must match path order"
+        );
+    }
+
+    #[test]
+    fn bitwise_ops() {
+        let a = H256::from([0b1010_1010u8; 32]);
+        let b = H256::from([0b0110_0110u8; 32]);
+
+        assert_eq!(a ^ b, H256::from([0b1100_1100u8; 32]));
+        assert_eq!(a & b, H256::from([0b0010_0010u8; 32]));
+        assert_eq!(a | b, H256::from([0b1110_1110u8; 32]));
+        assert_eq!(!a, H256::from([0b0101_0101u8; 32]));
+        assert!((a ^ a).is_zero());
+    }
+
+    #[test]
+    fn bloom_never_false_negative() {
+        let a = H256::from([0x42u8; 32]);
+        let b = H256::from([0x37u8; 32]);
+
+        let mut bloom = Bloom::zero();
+        bloom.accrue(&a);
+        assert!(bloom.contains(&a));
+
+        let mut other = Bloom::zero();
+        other.accrue(&b);
+        bloom.union(&other);
+        assert!(bloom.contains(&a));
+        assert!(bloom.contains(&b));
+    }
+
+    #[test]
+    fn hex_display_and_from_str() {
+        let h = H256::from([0xabu8; 32]);
+        let s = format!("{}", h);
+        assert_eq!(s.len(), 66);
+        assert!(s.starts_with("0x"));
+        assert_eq!(s.parse::<H256>().unwrap(), h);
+        // bare (un-prefixed) hex is also accepted
+        assert_eq!(H256::from_str(&s[2..]).unwrap(), h);
+        // wrong length is rejected
+        assert_eq!("0xdead".parse::<H256>(), Err(ParseHashError::InvalidLength));
+    }
+
+    #[test]
+    fn serde_round_trips_json_and_bincode() {
+        let h = H256::from([0x5au8; 32]);
+
+        let json = serde_json::to_string(&h).unwrap();
+        assert_eq!(json, format!("\"{:#x}\"", h));
+        assert_eq!(serde_json::from_str::<H256>(&json).unwrap(), h);
+        // non-borrowing human-readable deserializers must work too
+        assert_eq!(
+            serde_json::from_reader::<_, H256>(json.as_bytes()).unwrap(),
+            h
+        );
+        assert_eq!(
+            serde_json::from_value::<H256>(serde_json::Value::String(
+                format!("{:#x}", h)
+            ))
+            .unwrap(),
+            h
+        );
+
+        let bin = bincode::serialize(&h).unwrap();
+        // compact, length-prefix-free: exactly the raw bytes, no u64 length header
+        assert_eq!(bin.len(), 32);
+        assert_eq!(bincode::deserialize::<H256>(&bin).unwrap(), h);
+    }
+
+    #[cfg(feature = "quickcheck")]
+    quickcheck::quickcheck! {
+        fn fork_height_is_symmetric(a: H256, b: H256) -> bool {
+            a.fork_height(&b) == b.fork_height(&a)
+        }
+
+        fn parent_path_clears_low_bits(h: H256, height: u8) -> bool {
+            let parent = h.parent_path(height);
+            // every bit at or below `height` must be cleared in the parent path
+            (0..=height).all(|i| !parent.get_bit(i))
+        }
+
+        fn copy_bits_is_idempotent(h: H256, start: u8) -> bool {
+            let once = h.copy_bits(start);
+            once.copy_bits(start) == once
+        }
     }
 }